@@ -0,0 +1,217 @@
+use axum::async_trait;
+use redis::AsyncCommands;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::{GraphConn, RedisBackend};
+
+/// Generic read/write/invalidate operations shared by the dungeon response cache, so `crawl` and
+/// `make_dungeon` don't need to know whether entries live in Redis or in-process.
+///
+/// Values are (de)serialized as JSON; `invalidate` drops every key matching a `prefix:*` pattern.
+#[async_trait]
+pub(crate) trait CacheAdapter {
+    async fn get<T>(&self, key: &str) -> Option<T>
+    where
+        T: DeserializeOwned + Send;
+
+    async fn set<T>(&self, key: &str, value: &T, ttl: Option<Duration>)
+    where
+        T: Serialize + Sync;
+
+    async fn invalidate(&self, pattern: &str);
+}
+
+/// Either backend selectable via [`crate::Config`]; dispatches to whichever is configured.
+#[derive(Clone)]
+pub(crate) enum CacheBackend {
+    Memory(InMemoryCache),
+    Redis(RedisCache),
+}
+
+#[async_trait]
+impl CacheAdapter for CacheBackend {
+    async fn get<T>(&self, key: &str) -> Option<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        match self {
+            Self::Memory(cache) => cache.get(key).await,
+            Self::Redis(cache) => cache.get(key).await,
+        }
+    }
+
+    async fn set<T>(&self, key: &str, value: &T, ttl: Option<Duration>)
+    where
+        T: Serialize + Sync,
+    {
+        match self {
+            Self::Memory(cache) => cache.set(key, value, ttl).await,
+            Self::Redis(cache) => cache.set(key, value, ttl).await,
+        }
+    }
+
+    async fn invalidate(&self, pattern: &str) {
+        match self {
+            Self::Memory(cache) => cache.invalidate(pattern).await,
+            Self::Redis(cache) => cache.invalidate(pattern).await,
+        }
+    }
+}
+
+struct CacheEntry {
+    data: Vec<u8>,
+    expires_at: Option<Instant>,
+}
+
+impl CacheEntry {
+    fn is_expired(&self) -> bool {
+        self.expires_at.map_or(false, |at| Instant::now() >= at)
+    }
+}
+
+/// An in-process cache guarded by an `RwLock`, evicting expired entries lazily on read.
+///
+/// Cloning shares the same underlying map (via `Arc`), so every `Extension<CacheBackend>`
+/// extracted from a request sees the same entries.
+#[derive(Clone, Default)]
+pub(crate) struct InMemoryCache {
+    entries: Arc<RwLock<HashMap<String, CacheEntry>>>,
+}
+
+#[async_trait]
+impl CacheAdapter for InMemoryCache {
+    async fn get<T>(&self, key: &str) -> Option<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let mut entries = self.entries.write().expect("cache lock poisoned");
+
+        match entries.get(key) {
+            Some(entry) if entry.is_expired() => {
+                entries.remove(key);
+                None
+            }
+            Some(entry) => serde_json::from_slice(&entry.data).ok(),
+            None => None,
+        }
+    }
+
+    async fn set<T>(&self, key: &str, value: &T, ttl: Option<Duration>)
+    where
+        T: Serialize + Sync,
+    {
+        let Ok(data) = serde_json::to_vec(value) else {
+            return;
+        };
+
+        let entry = CacheEntry {
+            data,
+            expires_at: ttl.map(|ttl| Instant::now() + ttl),
+        };
+
+        self.entries
+            .write()
+            .expect("cache lock poisoned")
+            .insert(key.to_string(), entry);
+    }
+
+    async fn invalidate(&self, pattern: &str) {
+        let prefix = pattern.trim_end_matches('*');
+        self.entries
+            .write()
+            .expect("cache lock poisoned")
+            .retain(|k, _| !k.starts_with(prefix));
+    }
+}
+
+/// A Redis-backed cache storing JSON blobs via `SET ... EX` (TTL) and invalidating a pattern via
+/// `KEYS` + `DEL`. Reuses the server's existing [`RedisBackend`], so it shares the same pool in
+/// single-node mode.
+#[derive(Clone)]
+pub(crate) struct RedisCache {
+    backend: RedisBackend,
+}
+
+impl RedisCache {
+    pub(crate) fn new(backend: RedisBackend) -> Self {
+        Self { backend }
+    }
+}
+
+#[async_trait]
+impl CacheAdapter for RedisCache {
+    async fn get<T>(&self, key: &str) -> Option<T>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let mut conn = self.backend.connection().await.ok()?;
+        let data: Option<Vec<u8>> = conn.cache_get(key).await.ok()?;
+        serde_json::from_slice(&data?).ok()
+    }
+
+    async fn set<T>(&self, key: &str, value: &T, ttl: Option<Duration>)
+    where
+        T: Serialize + Sync,
+    {
+        let Ok(data) = serde_json::to_vec(value) else {
+            return;
+        };
+
+        if let Ok(mut conn) = self.backend.connection().await {
+            let _ = conn.cache_set(key, data, ttl).await;
+        }
+    }
+
+    async fn invalidate(&self, pattern: &str) {
+        let Ok(mut conn) = self.backend.connection().await else {
+            return;
+        };
+
+        if let Ok(keys) = conn.cache_keys(pattern).await {
+            if !keys.is_empty() {
+                let _ = conn.cache_del(&keys).await;
+            }
+        }
+    }
+}
+
+impl GraphConn {
+    async fn cache_get(&mut self, key: &str) -> Result<Option<Vec<u8>>, redis::RedisError> {
+        match self {
+            Self::Single(conn) => conn.get(key).await,
+            Self::Cluster(conn) => conn.get(key).await,
+        }
+    }
+
+    async fn cache_set(
+        &mut self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), redis::RedisError> {
+        match (self, ttl) {
+            (Self::Single(conn), Some(ttl)) => conn.set_ex(key, value, ttl.as_secs().max(1) as usize).await,
+            (Self::Single(conn), None) => conn.set(key, value).await,
+            (Self::Cluster(conn), Some(ttl)) => conn.set_ex(key, value, ttl.as_secs().max(1) as usize).await,
+            (Self::Cluster(conn), None) => conn.set(key, value).await,
+        }
+    }
+
+    async fn cache_keys(&mut self, pattern: &str) -> Result<Vec<String>, redis::RedisError> {
+        match self {
+            Self::Single(conn) => conn.keys(pattern).await,
+            Self::Cluster(conn) => conn.keys(pattern).await,
+        }
+    }
+
+    async fn cache_del(&mut self, keys: &[String]) -> Result<(), redis::RedisError> {
+        match self {
+            Self::Single(conn) => conn.del(keys).await,
+            Self::Cluster(conn) => conn.del(keys).await,
+        }
+    }
+}
@@ -6,6 +6,8 @@ use axum::{
     routing::{get, put},
     Json, Router,
 };
+use deadpool::managed::{Hook, HookError};
+use deadpool_redis::{Manager, Runtime, Timeouts};
 use derive_new::new;
 use itertools::Itertools;
 use petgraph::prelude::Graph;
@@ -13,15 +15,60 @@ use redis::{cmd, RedisError};
 use redis_graph::*;
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio::signal;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod cache;
+
+use cache::{CacheAdapter, CacheBackend, InMemoryCache, RedisCache};
+
 const DUNGEON: &str = "dungeon";
 
 #[derive(Debug, Clone, Deserialize, Default)]
 struct RedisConfig {
+    /// Comma-separated list of node URLs, e.g. `redis://a,redis://b,redis://c`.
+    ///
+    /// In single-node mode (the default) only the first URL is used.
     #[serde(default = "default_redis_url")]
     url: String,
+    /// Treat `url` as a list of Redis Cluster seed nodes instead of a single node.
+    #[serde(default)]
+    cluster: bool,
+    /// Max number of pooled connections (single-node mode only).
+    #[serde(default = "default_pool_max_size")]
+    pool_max_size: usize,
+    /// How long a request waits to acquire a pooled connection before giving up.
+    #[serde(default = "default_pool_timeout_secs")]
+    pool_timeout_secs: u64,
+    /// Max lifetime of a pooled connection: once it's been alive this long, it's dropped and
+    /// replaced instead of being handed out again, via a `pre_recycle` hook (`deadpool`'s
+    /// `Timeouts.recycle` only bounds how long the recycle *check* may take, it isn't an
+    /// age-based eviction knob).
+    #[serde(default = "default_pool_ttl_secs")]
+    pool_ttl_secs: u64,
+}
+
+impl RedisConfig {
+    /// The individual node URLs making up [`RedisConfig::url`].
+    fn nodes(&self) -> Vec<String> {
+        self.url.split(',').map(str::trim).map(String::from).collect()
+    }
+}
+
+const DEFAULT_POOL_MAX_SIZE: usize = 10;
+const fn default_pool_max_size() -> usize {
+    DEFAULT_POOL_MAX_SIZE
+}
+
+const DEFAULT_POOL_TIMEOUT_SECS: u64 = 5;
+const fn default_pool_timeout_secs() -> u64 {
+    DEFAULT_POOL_TIMEOUT_SECS
+}
+
+const DEFAULT_POOL_TTL_SECS: u64 = 300;
+const fn default_pool_ttl_secs() -> u64 {
+    DEFAULT_POOL_TTL_SECS
 }
 
 const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1:6379/";
@@ -38,6 +85,36 @@ struct Config {
     server_port: u16,
     #[serde(default)]
     redis: RedisConfig,
+    #[serde(default)]
+    cache: CacheConfig,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Default)]
+struct CacheConfig {
+    /// Which [`CacheBackend`] to cache dungeon responses in.
+    #[serde(default)]
+    backend: CacheBackendKind,
+    /// How long a cached `crawl` response stays valid.
+    #[serde(default = "default_cache_ttl_secs")]
+    ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CacheBackendKind {
+    Memory,
+    Redis,
+}
+
+impl Default for CacheBackendKind {
+    fn default() -> Self {
+        Self::Memory
+    }
+}
+
+const DEFAULT_CACHE_TTL_SECS: u64 = 60;
+const fn default_cache_ttl_secs() -> u64 {
+    DEFAULT_CACHE_TTL_SECS
 }
 
 const DEFAULT_RUST_LOG: &str = "server=debug";
@@ -60,8 +137,177 @@ impl Config {
     }
 }
 
+/// A handle to either a single-node Redis instance or a Redis Cluster deployment.
+///
+/// Both variants implement `redis_graph`'s `GraphCommands`/`GraphCommandsAsync` traits, so the
+/// handlers below can stay oblivious to which topology is actually backing them. The single-node
+/// case is pooled via `deadpool_redis`; cluster connections are left unpooled since
+/// `redis-rs` doesn't ship a `deadpool`/`bb8` manager for them.
+#[derive(Clone)]
+pub(crate) enum RedisBackend {
+    Single(deadpool_redis::Pool),
+    Cluster(redis::cluster::ClusterClient),
+}
+
+impl RedisBackend {
+    fn new(cfg: &RedisConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        if cfg.cluster {
+            let client = redis::cluster::ClusterClient::new(cfg.nodes())?;
+            Ok(Self::Cluster(client))
+        } else {
+            // only the first node is used in single-node mode; see `RedisConfig::url`'s doc comment
+            let url = cfg.nodes().into_iter().next().unwrap_or_else(|| cfg.url.clone());
+            let manager = Manager::new(url)?;
+            let wait = Duration::from_secs(cfg.pool_timeout_secs);
+            let ttl = Duration::from_secs(cfg.pool_ttl_secs);
+
+            let pool = deadpool_redis::Pool::builder(manager)
+                .max_size(cfg.pool_max_size)
+                .timeouts(Timeouts {
+                    wait: Some(wait),
+                    create: Some(wait),
+                    recycle: Some(wait),
+                })
+                // deadpool has no age-based eviction of its own, so enforce the TTL ourselves:
+                // reject recycling a connection once it's older than `ttl`, which makes deadpool
+                // drop it and create a fresh one instead of handing it back out.
+                .pre_recycle(Hook::sync_fn(move |_conn, metrics| {
+                    if metrics.created.elapsed() >= ttl {
+                        Err(HookError::Continue(None))
+                    } else {
+                        Ok(())
+                    }
+                }))
+                .runtime(Runtime::Tokio1)
+                .build()?;
+
+            Ok(Self::Single(pool))
+        }
+    }
+
+    pub(crate) async fn connection(&self) -> Result<GraphConn, ConnError> {
+        match self {
+            Self::Single(pool) => {
+                let conn = pool.get().await?;
+                Ok(GraphConn::Single(conn))
+            }
+            Self::Cluster(client) => {
+                let conn = client.get_async_connection().await?;
+                Ok(GraphConn::Cluster(conn))
+            }
+        }
+    }
+}
+
+/// Failure to obtain a [`GraphConn`], distinguishing pool exhaustion (mapped to `503`) from
+/// regular Redis errors (mapped to `500` via [`internal_error`]).
+#[derive(Debug)]
+enum ConnError {
+    Redis(RedisError),
+    PoolExhausted,
+}
+
+impl std::fmt::Display for ConnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Redis(e) => write!(f, "{e}"),
+            Self::PoolExhausted => write!(f, "timed out acquiring a Redis connection from the pool"),
+        }
+    }
+}
+
+impl std::error::Error for ConnError {}
+
+impl From<RedisError> for ConnError {
+    fn from(e: RedisError) -> Self {
+        Self::Redis(e)
+    }
+}
+
+impl From<deadpool_redis::PoolError> for ConnError {
+    fn from(e: deadpool_redis::PoolError) -> Self {
+        match e {
+            deadpool_redis::PoolError::Timeout(_) => Self::PoolExhausted,
+            deadpool_redis::PoolError::Backend(e) => Self::Redis(e),
+            e => Self::Redis(RedisError::from(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                e.to_string(),
+            ))),
+        }
+    }
+}
+
+/// A pooled/ad-hoc connection to either a single Redis node or a cluster.
+pub(crate) enum GraphConn {
+    Single(deadpool_redis::Connection),
+    Cluster(redis::cluster_async::ClusterConnection),
+}
+
+impl GraphConn {
+    async fn graph_query(
+        &mut self,
+        graph: &str,
+        query: String,
+    ) -> Result<GraphResultSet, RedisError> {
+        match self {
+            Self::Single(conn) => conn.graph_query(graph, query).await,
+            Self::Cluster(conn) => conn.graph_query(graph, query).await,
+        }
+    }
+
+    async fn graph_ro_query(
+        &mut self,
+        graph: &str,
+        query: &str,
+    ) -> Result<GraphResultSet, RedisError> {
+        match self {
+            Self::Single(conn) => conn.graph_ro_query(graph, query).await,
+            Self::Cluster(conn) => conn.graph_ro_query(graph, query).await,
+        }
+    }
+}
+
+/// An error from a [`GraphStore`], decoupled from `redis::RedisError` so mock stores used in
+/// tests don't need a live Redis connection to produce one.
+#[derive(Debug)]
+struct GraphError(String);
+
+impl std::fmt::Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+impl From<RedisError> for GraphError {
+    fn from(e: RedisError) -> Self {
+        Self(e.to_string())
+    }
+}
+
+/// Abstraction over the handful of RedisGraph operations the handlers need, so they can be
+/// exercised against an in-memory mock instead of a live RedisGraph instance.
+#[async_trait]
+trait GraphStore {
+    async fn run(&mut self, graph: &str, query: String) -> Result<GraphResultSet, GraphError>;
+
+    async fn run_ro(&mut self, graph: &str, query: &str) -> Result<GraphResultSet, GraphError>;
+}
+
+#[async_trait]
+impl GraphStore for GraphConn {
+    async fn run(&mut self, graph: &str, query: String) -> Result<GraphResultSet, GraphError> {
+        Ok(self.graph_query(graph, query).await?)
+    }
+
+    async fn run_ro(&mut self, graph: &str, query: &str) -> Result<GraphResultSet, GraphError> {
+        Ok(self.graph_ro_query(graph, query).await?)
+    }
+}
+
 #[repr(transparent)]
-struct RedisConn(redis::aio::Connection);
+struct RedisConn(GraphConn);
 
 #[async_trait]
 impl<B> FromRequest<B> for RedisConn
@@ -71,19 +317,25 @@ where
     type Rejection = (StatusCode, String);
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        let Extension(client) = Extension::<redis::Client>::from_request(req)
+        let Extension(backend) = Extension::<RedisBackend>::from_request(req)
             .await
             .map_err(internal_error)?;
 
-        let conn = client
-            .get_async_connection()
-            .await
-            .map_err(internal_error)?;
+        let conn = backend.connection().await.map_err(conn_rejection)?;
 
         Ok(Self(conn))
     }
 }
 
+/// Map a [`ConnError`] to the HTTP response the `RedisConn` extractor rejects with: pool
+/// exhaustion becomes a retryable `503`, everything else is an opaque `500`.
+fn conn_rejection(err: ConnError) -> (StatusCode, String) {
+    match err {
+        ConnError::PoolExhausted => (StatusCode::SERVICE_UNAVAILABLE, err.to_string()),
+        ConnError::Redis(e) => internal_error(e),
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "lowercase")]
 enum Response<T> {
@@ -110,18 +362,27 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // create Redis client
-    // TODO: connection pooling (use deadpool_redis)
+    // create Redis client, pooled via deadpool_redis in single-node mode
+    tracing::debug!(
+        "connecting to Redis at '{}' (cluster={})",
+        cfg.redis.url,
+        cfg.redis.cluster
+    );
+    let backend = RedisBackend::new(&cfg.redis).expect("Redis client");
 
-    tracing::debug!("connecting to Redis at '{}'", cfg.redis.url);
-    let client = redis::Client::open(cfg.redis.url).expect("Redis client");
+    let cache = match cfg.cache.backend {
+        CacheBackendKind::Memory => CacheBackend::Memory(InMemoryCache::default()),
+        CacheBackendKind::Redis => CacheBackend::Redis(RedisCache::new(backend.clone())),
+    };
 
     // setup request routing with shared Redis pool
     let app = Router::new()
         .route("/", get(health))
         .route("/crawl", get(crawl))
         .route("/dungeon", put(make_dungeon))
-        .layer(Extension(client));
+        .layer(Extension(backend))
+        .layer(Extension(cache))
+        .layer(Extension(cfg.cache));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], cfg.server_port));
 
@@ -173,15 +434,24 @@ struct Health {
     redis: Status,
 }
 
-async fn health(Extension(client): Extension<redis::Client>) -> impl IntoResponse {
+async fn health(Extension(backend): Extension<RedisBackend>) -> impl IntoResponse {
     // check redis
-    // TODO: replace unwrap with redis: Status::Err
-    let mut conn = client.get_async_connection().await.unwrap();
-    let reply: Result<String, RedisError> = cmd("PING").query_async(&mut conn).await;
-
-    let redis = match reply {
-        Ok(reply) if reply == "PONG" => Status::Ok,
-        _ => Status::Err,
+    let redis = match backend.connection().await {
+        Ok(GraphConn::Single(mut conn)) => {
+            let reply: Result<String, RedisError> = cmd("PING").query_async(&mut conn).await;
+            match reply {
+                Ok(reply) if reply == "PONG" => Status::Ok,
+                _ => Status::Err,
+            }
+        }
+        Ok(GraphConn::Cluster(mut conn)) => {
+            let reply: Result<String, RedisError> = cmd("PING").query_async(&mut conn).await;
+            match reply {
+                Ok(reply) if reply == "PONG" => Status::Ok,
+                _ => Status::Err,
+            }
+        }
+        Err(_) => Status::Err,
     };
 
     let health = Health {
@@ -229,10 +499,23 @@ struct Treasure {
     gp: u32,
 }
 
+// kept outside the "dungeon:" namespace so `invalidate(DUNGEON_CACHE_PATTERN)` doesn't wipe it
+const DUNGEON_VERSION_KEY: &str = "dungeon_version";
+const DUNGEON_CACHE_PATTERN: &str = "dungeon:*";
+
 async fn make_dungeon(
     RedisConn(mut conn): RedisConn,
+    Extension(cache): Extension<CacheBackend>,
     Query(params): Query<DungeonParams>,
 ) -> impl IntoResponse {
+    make_dungeon_with(&mut conn, &cache, params).await
+}
+
+async fn make_dungeon_with<S: GraphStore>(
+    store: &mut S,
+    cache: &CacheBackend,
+    params: DungeonParams,
+) -> (StatusCode, String) {
     if params.size == 0 {
         return (
             StatusCode::BAD_REQUEST,
@@ -248,8 +531,7 @@ async fn make_dungeon(
     }
 
     // clear current dungeon graph
-    let res: Result<GraphResultSet, RedisError> =
-        conn.graph_query(DUNGEON, "MATCH (n) DETACH DELETE n").await;
+    let res = store.run(DUNGEON, "MATCH (n) DETACH DELETE n".to_string()).await;
 
     if let Err(e) = res {
         return internal_error(e);
@@ -337,16 +619,24 @@ async fn make_dungeon(
         .chain(place_treasures)
         .join("\n");
 
-    let res: Result<GraphResultSet, RedisError> = conn.graph_query(DUNGEON, query).await;
+    let res = store.run(DUNGEON, query).await;
 
     match res {
-        Ok(_) => (StatusCode::CREATED, String::new()),
+        Ok(_) => {
+            // invalidate any cached crawl results and bump the version so future cache keys
+            // don't collide with them
+            let version = cache.get::<u64>(DUNGEON_VERSION_KEY).await.unwrap_or(0) + 1;
+            cache.set(DUNGEON_VERSION_KEY, &version, None).await;
+            cache.invalidate(DUNGEON_CACHE_PATTERN).await;
+
+            (StatusCode::CREATED, String::new())
+        }
         Err(e) => internal_error(e),
     }
 }
 
 /// the output of `crawl` handler
-#[derive(Default, Serialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 struct Crawl {
     // TODO: path: Vec<u16> or better Vec<(Room, Option<Treasure>)>
     /// the shortest path to the largest treasure in the dungeon
@@ -355,7 +645,26 @@ struct Crawl {
     gp: u32,
 }
 
-async fn crawl(RedisConn(mut conn): RedisConn) -> impl IntoResponse {
+async fn crawl(
+    RedisConn(mut conn): RedisConn,
+    Extension(cache): Extension<CacheBackend>,
+    Extension(cache_cfg): Extension<CacheConfig>,
+) -> impl IntoResponse {
+    crawl_with(&mut conn, &cache, Duration::from_secs(cache_cfg.ttl_secs)).await
+}
+
+async fn crawl_with<S: GraphStore>(
+    store: &mut S,
+    cache: &CacheBackend,
+    ttl: Duration,
+) -> (StatusCode, Json<Response<Crawl>>) {
+    let version = cache.get::<u64>(DUNGEON_VERSION_KEY).await.unwrap_or(0);
+    let cache_key = format!("dungeon:{version}:crawl");
+
+    if let Some(crawl) = cache.get::<Crawl>(&cache_key).await {
+        return (StatusCode::OK, Json(Response::Data(crawl)));
+    }
+
     // TODO: return the total treasure gp on the path
     // TODO: entrance/start is currenlty hard-coded
     //  => generate special :Entrance node or take start as param
@@ -370,7 +679,7 @@ async fn crawl(RedisConn(mut conn): RedisConn) -> impl IntoResponse {
 		RETURN shortestPath((start)-[:LEADS_TO*]->(stop)) AS path
 	"#;
 
-    let res: Result<GraphResultSet, RedisError> = conn.graph_ro_query(DUNGEON, query).await;
+    let res = store.run_ro(DUNGEON, query).await;
 
     match res {
         Ok(res) => {
@@ -385,6 +694,8 @@ async fn crawl(RedisConn(mut conn): RedisConn) -> impl IntoResponse {
                         gp: 0,
                     };
 
+                    cache.set(&cache_key, &crawl, Some(ttl)).await;
+
                     return (StatusCode::OK, Json(Response::Data(crawl)));
                 }
             }
@@ -401,3 +712,195 @@ async fn crawl(RedisConn(mut conn): RedisConn) -> impl IntoResponse {
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory [`GraphStore`] that records every query it's asked to run and replays a
+    /// single canned response, so handlers can be tested without a live RedisGraph instance.
+    #[derive(Default)]
+    struct MockGraphStore {
+        queries: Vec<String>,
+        next: Option<Result<GraphResultSet, GraphError>>,
+    }
+
+    impl MockGraphStore {
+        fn with_response(res: Result<GraphResultSet, GraphError>) -> Self {
+            Self {
+                queries: Vec::new(),
+                next: Some(res),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl GraphStore for MockGraphStore {
+        async fn run(&mut self, _graph: &str, query: String) -> Result<GraphResultSet, GraphError> {
+            self.queries.push(query);
+            self.next.take().unwrap_or_else(|| Ok(GraphResultSet::default()))
+        }
+
+        async fn run_ro(
+            &mut self,
+            _graph: &str,
+            query: &str,
+        ) -> Result<GraphResultSet, GraphError> {
+            self.queries.push(query.to_string());
+            self.next.take().unwrap_or_else(|| Ok(GraphResultSet::default()))
+        }
+    }
+
+    fn params(size: u16, maxgp: u32, max_treasures: u8) -> DungeonParams {
+        DungeonParams {
+            size,
+            maxgp,
+            max_treasures,
+        }
+    }
+
+    fn cache() -> CacheBackend {
+        CacheBackend::Memory(InMemoryCache::default())
+    }
+
+    #[test]
+    fn single_node_backend_uses_only_the_first_of_several_comma_separated_urls() {
+        let cfg = RedisConfig {
+            url: "redis://a,redis://b".to_string(),
+            cluster: false,
+            ..RedisConfig::default()
+        };
+
+        // `Manager::new` would fail to parse the raw, unsplit comma list; this must not panic
+        RedisBackend::new(&cfg).expect("single-node backend should only see the first URL");
+    }
+
+    #[tokio::test]
+    async fn make_dungeon_rejects_zero_size() {
+        let mut store = MockGraphStore::default();
+
+        let (status, body) = make_dungeon_with(&mut store, &cache(), params(0, 10, 3)).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert!(body.contains("size"));
+        assert!(store.queries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn make_dungeon_rejects_zero_maxgp() {
+        let mut store = MockGraphStore::default();
+
+        let (status, body) = make_dungeon_with(&mut store, &cache(), params(4, 0, 3)).await;
+
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert!(body.contains("maxgp"));
+        assert!(store.queries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn make_dungeon_emits_rooms_corridors_and_treasures() {
+        let mut store = MockGraphStore::default();
+
+        let (status, _) = make_dungeon_with(&mut store, &cache(), params(4, 10, 3)).await;
+
+        assert_eq!(StatusCode::CREATED, status);
+
+        // first query clears the existing graph, the second builds the new one
+        assert_eq!(2, store.queries.len());
+        assert!(store.queries[0].contains("DETACH DELETE"));
+
+        let build_query = &store.queries[1];
+        assert!(build_query.contains("CREATE (r0:Room"));
+        assert!(build_query.contains("LEADS_TO"));
+        assert!(build_query.contains("CONTAINS"));
+    }
+
+    #[tokio::test]
+    async fn make_dungeon_bumps_version_and_invalidates_cache_on_success() {
+        let mut store = MockGraphStore::default();
+        let cache = cache();
+
+        cache.set("dungeon:0:crawl", &Crawl::default(), None).await;
+
+        let (status, _) = make_dungeon_with(&mut store, &cache, params(4, 10, 3)).await;
+
+        assert_eq!(StatusCode::CREATED, status);
+        assert_eq!(Some(1u64), cache.get::<u64>(DUNGEON_VERSION_KEY).await);
+        assert_eq!(None, cache.get::<Crawl>("dungeon:0:crawl").await);
+    }
+
+    #[tokio::test]
+    async fn crawl_returns_not_found_on_empty_result_set() {
+        let mut store = MockGraphStore::with_response(Ok(GraphResultSet::default()));
+
+        let (status, Json(body)) = crawl_with(&mut store, &cache(), Duration::from_secs(60)).await;
+
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert!(matches!(body, Response::Data(_)));
+    }
+
+    /// A single-row [`GraphResultSet`] with one scalar column, as `run_ro` would hand back for a
+    /// query like `crawl_with`'s `RETURN ... AS path`.
+    fn result_set_with_scalar(key: &str, value: &str) -> GraphResultSet {
+        let mut data = std::collections::HashMap::new();
+        data.insert(
+            key.to_string(),
+            GraphValue::Scalar(redis::Value::Data(value.as_bytes().to_vec())),
+        );
+
+        GraphResultSet {
+            header: vec![key.to_string()],
+            data: vec![GraphResult { data }],
+            metadata: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn crawl_returns_the_path_found_by_the_store() {
+        let mut store =
+            MockGraphStore::with_response(Ok(result_set_with_scalar("path", "room1,room2")));
+
+        let (status, Json(body)) = crawl_with(&mut store, &cache(), Duration::from_secs(60)).await;
+
+        assert_eq!(StatusCode::OK, status);
+        assert!(matches!(body, Response::Data(Crawl { path, .. }) if path == "room1,room2"));
+        assert_eq!(1, store.queries.len());
+    }
+
+    #[tokio::test]
+    async fn crawl_returns_not_found_when_path_column_is_missing() {
+        // a non-empty result without the "path" column `crawl_with` reads (e.g. the query's
+        // RETURN shape changed) must fall through to 404, not panic or serve garbage
+        let mut store =
+            MockGraphStore::with_response(Ok(result_set_with_scalar("not_path", "room1,room2")));
+
+        let (status, Json(body)) = crawl_with(&mut store, &cache(), Duration::from_secs(60)).await;
+
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert!(matches!(body, Response::Data(_)));
+    }
+
+    #[tokio::test]
+    async fn crawl_surfaces_graph_store_errors() {
+        let mut store = MockGraphStore::with_response(Err(GraphError("boom".to_string())));
+
+        let (status, Json(body)) = crawl_with(&mut store, &cache(), Duration::from_secs(60)).await;
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+        assert!(matches!(body, Response::Error(msg) if msg == "boom"));
+    }
+
+    #[tokio::test]
+    async fn crawl_serves_cached_result_without_querying_the_store() {
+        let cache = cache();
+        cache.set("dungeon:0:crawl", &Crawl { path: "cached".to_string(), gp: 7 }, None).await;
+
+        let mut store = MockGraphStore::default();
+
+        let (status, Json(body)) = crawl_with(&mut store, &cache, Duration::from_secs(60)).await;
+
+        assert_eq!(StatusCode::OK, status);
+        assert!(matches!(body, Response::Data(Crawl { path, .. }) if path == "cached"));
+        assert!(store.queries.is_empty());
+    }
+}
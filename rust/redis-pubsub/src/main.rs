@@ -27,16 +27,18 @@ async fn main() -> redis::RedisResult<()> {
 
     let client = redis::Client::open(redis_url).unwrap();
 
-    let mut publish_conn = client.get_async_connection().await?;
-    let mut pubsub_conn = client.get_async_connection().await?.into_pubsub();
-
-    pubsub_conn.subscribe(inbox(replica_id)).await?;
-    let mut pubsub_stream = pubsub_conn.on_message();
+    // one connection end-to-end: publish registrations first, then switch the same connection
+    // into pubsub mode to listen, rather than opening a second dedicated connection for each half
+    let mut conn = client.get_async_connection().await?;
 
     for recipient in (0..replica_id).map(inbox) {
-        publish_conn.publish(recipient, replica_id).await?;
+        conn.publish(recipient, replica_id).await?;
     }
 
+    let mut pubsub_conn = conn.into_pubsub();
+    pubsub_conn.subscribe(inbox(replica_id)).await?;
+    let mut pubsub_stream = pubsub_conn.on_message();
+
     while let Some(msg) = pubsub_stream.next().await {
         let i: usize = msg.get_payload()?;
         info!("registered {}", i);
@@ -1,9 +1,8 @@
-use bb8_redis::{bb8, RedisConnectionManager};
 use tracing::{debug, info, warn};
 
 use std::env;
 
-use redis_stream_wc::{send, Message, DEAULT_REDIS_URL, DEAULT_STREAM_ID};
+use redis_stream_wc::{send, Message, RedisBackend, DEAULT_REDIS_URL, DEAULT_STREAM_ID};
 
 const DEFAULT_REPEATS: usize = 10;
 const DEFAULT_DATA: &str = "hello world";
@@ -37,10 +36,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         DEAULT_REDIS_URL.to_owned()
     });
 
-    // Note: This is just an example of connection pooling. In this particular case it's not
-    // actually necessary.
-    let manager = RedisConnectionManager::new(redis_url)?;
-    let pool = bb8::Pool::builder().max_size(1).build(manager).await?;
+    let cluster = env::var("REDIS_CLUSTER")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+
+    let backend = RedisBackend::connect(&redis_url, cluster).await?;
 
     let data = DEFAULT_DATA.to_owned();
     info!(
@@ -54,7 +54,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             data: data.clone(),
         };
         debug!("Client '{}' seding: {:?}", &client_id, &msg);
-        send(&pool, counter_addr.clone(), msg).await;
+        send(&backend, counter_addr.clone(), msg).await?;
     }
 
     info!(
@@ -62,13 +62,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         &client_id, &counter_addr
     );
     send(
-        &pool,
+        &backend,
         counter_addr.clone(),
         Message::Disconnect {
             client_id: client_id.clone(),
         },
     )
-    .await;
+    .await?;
 
     info!("Client '{}' terminated", client_id);
     Ok(())
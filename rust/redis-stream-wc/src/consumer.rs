@@ -0,0 +1,459 @@
+use redis::streams::{StreamId, StreamKey, StreamReadOptions, StreamReadReply};
+use redis::{AsyncCommands, Commands};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::{Error, Message, RedisBackend};
+
+/// Initial backoff before retrying a recoverable error out of the fetch loop; doubled (capped at
+/// [`MAX_BACKOFF`]) after each consecutive failure, reset on success.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Capacity of the channel handing decoded entries from the fetcher to the counting loop, as a
+/// multiple of the read batch size. Bounding it (rather than using an unbounded channel) is what
+/// gives the fetcher backpressure: once counting falls behind, `tx.send` blocks and `XREADGROUP`
+/// naturally stops being polled, so in-flight memory is capped by the channel, not by however much
+/// Redis has queued.
+const CHANNEL_CAPACITY_FACTOR: usize = 2;
+
+/// A decoded (or undecodable) stream entry handed from the fetcher to the counting loop, together
+/// with the stream key it was read from.
+struct FetchedEntry {
+    key: String,
+    id: String,
+    message: Result<Message, Error>,
+}
+
+/// Suffix appended to a stream key to get its dead-letter list, where the IDs of entries that fail
+/// to decode into a [`Message`] are pushed instead of being retried forever. The entries
+/// themselves stay in the stream.
+pub const DEAD_LETTER_SUFFIX: &str = ":dead";
+
+/// Default minimum idle time before a pending entry is considered abandoned and reclaimed from
+/// whichever (possibly dead) consumer it was delivered to.
+const DEFAULT_MIN_IDLE: Duration = Duration::from_secs(30);
+
+/// What a [`Consumer::run`] handler returns after processing a [`Message`].
+pub enum Progress {
+    Continue,
+    Terminate,
+}
+
+/// A fault-tolerant, consumer-group based reader over a single Redis Stream.
+///
+/// Entries that fail to decode (missing `msg` key, non-`Data` payload, invalid JSON, ...) are
+/// logged, `XACK`ed (so they aren't redelivered forever) and have their ID recorded on the
+/// stream's dead-letter list rather than panicking the poll loop. Multiple `Consumer`s sharing the same `group` split
+/// the stream between them, so work scales horizontally with the number of consumers.
+#[derive(Clone)]
+pub struct Consumer {
+    backend: RedisBackend,
+    stream: String,
+    group: String,
+    consumer: String,
+    batch: usize,
+    block: Duration,
+    min_idle: Duration,
+}
+
+impl Consumer {
+    pub fn new(backend: RedisBackend, stream: String, group: String, consumer: String) -> Self {
+        Self {
+            backend,
+            stream,
+            group,
+            consumer,
+            batch: 64,
+            block: Duration::from_secs(0),
+            min_idle: DEFAULT_MIN_IDLE,
+        }
+    }
+
+    /// Max number of entries fetched per `XREADGROUP`/reclaimed per `XPENDING` call.
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// How long a single `XREADGROUP` call blocks waiting for new entries (`0` blocks forever).
+    pub fn block(mut self, block: Duration) -> Self {
+        self.block = block;
+        self
+    }
+
+    /// How long an entry must have gone unacknowledged before [`Consumer::run`] reclaims it from
+    /// whichever consumer it was originally delivered to.
+    pub fn min_idle(mut self, min_idle: Duration) -> Self {
+        self.min_idle = min_idle;
+        self
+    }
+
+    fn dead_letter_key(&self) -> String {
+        format!("{}{}", self.stream, DEAD_LETTER_SUFFIX)
+    }
+
+    /// Create the consumer group (and the stream, via `MKSTREAM`) if it doesn't exist yet. Starts
+    /// the group at `$`: a freshly created group only sees entries added from now on, since any
+    /// pre-existing backlog is out of scope for at-least-once delivery anyway.
+    pub async fn ensure_group(&self) -> Result<(), Error> {
+        let res: Result<(), Error> = match &self.backend {
+            RedisBackend::Single(conn) => {
+                let mut conn = conn.clone();
+                redis::cmd("XGROUP")
+                    .arg("CREATE")
+                    .arg(&self.stream)
+                    .arg(&self.group)
+                    .arg("$")
+                    .arg("MKSTREAM")
+                    .query_async(&mut *conn)
+                    .await
+                    .map_err(Error::from)
+            }
+            RedisBackend::Cluster(client) => {
+                let client = client.clone();
+                let group = self.group.clone();
+                let stream = self.stream.clone();
+                tokio::task::spawn_blocking(move || -> Result<(), Error> {
+                    // a connection this acquire can't get back is fatal, not a transient read
+                    // failure: surface it as PoolUnavailable so callers like `fetch_loop` give up
+                    // instead of retrying forever
+                    let mut conn = client
+                        .get_connection()
+                        .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
+                    redis::cmd("XGROUP")
+                        .arg("CREATE")
+                        .arg(&stream)
+                        .arg(&group)
+                        .arg("$")
+                        .arg("MKSTREAM")
+                        .query(&mut conn)
+                        .map_err(Error::from)
+                })
+                .await
+                .unwrap_or_else(|e| Err(Error::TaskPanicked(e.to_string())))
+            }
+        };
+
+        // the group already existing is not an error for us
+        match res {
+            Ok(()) => Ok(()),
+            Err(e) if e.to_string().contains("BUSYGROUP") => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Push the ID of an undecodable stream entry onto the dead-letter list and `XACK` it so it
+    /// isn't redelivered. The entry itself is left in the stream.
+    async fn dead_letter(&self, id: &str, reason: &str) {
+        warn!(
+            "SKIP: entry {} on stream '{}' failed to decode: {}",
+            id, self.stream, reason
+        );
+
+        let dead_letter = self.dead_letter_key();
+
+        let ack: Result<(), Error> = match &self.backend {
+            RedisBackend::Single(conn) => {
+                let mut conn = conn.clone();
+                let _: Result<i64, redis::RedisError> = conn.rpush(&dead_letter, id).await;
+                conn.xack(&self.stream, &self.group, &[id])
+                    .await
+                    .map_err(Error::from)
+            }
+            RedisBackend::Cluster(client) => {
+                let client = client.clone();
+                let stream = self.stream.clone();
+                let group = self.group.clone();
+                let dead_letter = dead_letter.clone();
+                let id = id.to_owned();
+                tokio::task::spawn_blocking(move || -> Result<(), Error> {
+                    let mut conn = client
+                        .get_connection()
+                        .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
+                    let _: i64 = conn.rpush(&dead_letter, &id)?;
+                    conn.xack(&stream, &group, &[id]).map_err(Error::from)
+                })
+                .await
+                .unwrap_or_else(|e| Err(Error::TaskPanicked(e.to_string())))
+            }
+        };
+
+        if let Err(e) = ack {
+            warn!("failed to XACK dead-lettered entry {}: {}", id, e);
+        }
+    }
+
+    async fn xack(&self, id: &str) {
+        let res: Result<(), Error> = match &self.backend {
+            RedisBackend::Single(conn) => conn
+                .clone()
+                .xack(&self.stream, &self.group, &[id])
+                .await
+                .map_err(Error::from),
+            RedisBackend::Cluster(client) => {
+                let client = client.clone();
+                let stream = self.stream.clone();
+                let group = self.group.clone();
+                let id = id.to_owned();
+                tokio::task::spawn_blocking(move || -> Result<(), Error> {
+                    let mut conn = client
+                        .get_connection()
+                        .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
+                    conn.xack(&stream, &group, &[id]).map_err(Error::from)
+                })
+                .await
+                .unwrap_or_else(|e| Err(Error::TaskPanicked(e.to_string())))
+            }
+        };
+
+        if let Err(e) = res {
+            warn!("failed to XACK entry {}: {}", id, e);
+        }
+    }
+
+    async fn read(&self, opts: &StreamReadOptions) -> Result<StreamReadReply, Error> {
+        match &self.backend {
+            RedisBackend::Single(conn) => conn
+                .clone()
+                .xread_options(&[&self.stream], &[">"], opts)
+                .await
+                .map_err(Error::from),
+            RedisBackend::Cluster(client) => {
+                let client = client.clone();
+                let stream = self.stream.clone();
+                let opts = opts.clone();
+                tokio::task::spawn_blocking(move || -> Result<StreamReadReply, Error> {
+                    let mut conn = client
+                        .get_connection()
+                        .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
+                    conn.xread_options(&[&stream], &[">"], &opts)
+                        .map_err(Error::from)
+                })
+                .await
+                .unwrap_or_else(|e| Err(Error::TaskPanicked(e.to_string())))
+            }
+        }
+    }
+
+    /// IDs of entries that have been pending (delivered but unacknowledged) for at least
+    /// `self.min_idle`, regardless of which consumer they were delivered to.
+    async fn abandoned_ids(&self) -> Result<Vec<String>, Error> {
+        let min_idle_ms = self.min_idle.as_millis() as i64;
+        let batch = self.batch as i64;
+
+        let entries: Vec<(String, String, i64, i64)> = match &self.backend {
+            RedisBackend::Single(conn) => {
+                let mut conn = conn.clone();
+                redis::cmd("XPENDING")
+                    .arg(&self.stream)
+                    .arg(&self.group)
+                    .arg("IDLE")
+                    .arg(min_idle_ms)
+                    .arg("-")
+                    .arg("+")
+                    .arg(batch)
+                    .query_async(&mut conn)
+                    .await?
+            }
+            RedisBackend::Cluster(client) => {
+                let client = client.clone();
+                let stream = self.stream.clone();
+                let group = self.group.clone();
+                tokio::task::spawn_blocking(move || -> Result<_, Error> {
+                    let mut conn = client
+                        .get_connection()
+                        .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
+                    redis::cmd("XPENDING")
+                        .arg(&stream)
+                        .arg(&group)
+                        .arg("IDLE")
+                        .arg(min_idle_ms)
+                        .arg("-")
+                        .arg("+")
+                        .arg(batch)
+                        .query(&mut conn)
+                        .map_err(Error::from)
+                })
+                .await
+                .map_err(|e| Error::TaskPanicked(e.to_string()))??
+            }
+        };
+
+        Ok(entries.into_iter().map(|(id, ..)| id).collect())
+    }
+
+    /// Claim the given entry IDs for this consumer via `XAUTOCLAIM`'s `XCLAIM` counterpart,
+    /// returning their current data so they can be replayed through `handle`.
+    async fn claim(&self, ids: &[String]) -> Result<Vec<StreamId>, Error> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let min_idle_ms = self.min_idle.as_millis() as i64;
+
+        let reply: redis::streams::StreamClaimReply = match &self.backend {
+            RedisBackend::Single(conn) => {
+                conn.clone()
+                    .xclaim(&self.stream, &self.group, &self.consumer, min_idle_ms, ids)
+                    .await?
+            }
+            RedisBackend::Cluster(client) => {
+                let client = client.clone();
+                let stream = self.stream.clone();
+                let group = self.group.clone();
+                let consumer = self.consumer.clone();
+                let ids = ids.to_vec();
+                tokio::task::spawn_blocking(move || -> Result<_, Error> {
+                    let mut conn = client
+                        .get_connection()
+                        .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
+                    conn.xclaim(&stream, &group, &consumer, min_idle_ms, &ids)
+                        .map_err(Error::from)
+                })
+                .await
+                .map_err(|e| Error::TaskPanicked(e.to_string()))??
+            }
+        };
+
+        Ok(reply.ids)
+    }
+
+    /// Reclaim entries abandoned by dead consumers and replay them through `handle` before
+    /// consuming anything new. Returns `true` if `handle` reported [`Progress::Terminate`].
+    async fn reclaim<F, Fut>(&self, handle: &mut F) -> Result<bool, Error>
+    where
+        F: FnMut(&str, Message) -> Fut,
+        Fut: Future<Output = Progress>,
+    {
+        let abandoned = self.abandoned_ids().await?;
+
+        if abandoned.is_empty() {
+            return Ok(false);
+        }
+
+        info!(
+            "reclaiming {} abandoned entries from stream '{}'",
+            abandoned.len(),
+            self.stream
+        );
+
+        for StreamId { id, map } in self.claim(&abandoned).await? {
+            match Message::try_from(map) {
+                Ok(msg) => {
+                    let terminate = matches!(handle(&id, msg).await, Progress::Terminate);
+                    self.xack(&id).await;
+                    if terminate {
+                        return Ok(true);
+                    }
+                }
+                Err(e) => self.dead_letter(&id, &e.to_string()).await,
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Issue `XREADGROUP` in a loop and push each decoded (or undecodable) entry into `tx`,
+    /// reusing one buffer across iterations instead of allocating a fresh `flat_map` collection
+    /// per round. Blocks on `tx.send` when the counting side is behind, which is what bounds
+    /// memory to the channel's capacity rather than to Redis's queue. Stops once the receiving
+    /// end is dropped (the counting loop terminated) or a fatal error is hit.
+    async fn fetch_loop(&self, tx: mpsc::Sender<FetchedEntry>) -> Result<(), Error> {
+        let group_opts = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .count(self.batch)
+            .block(self.block.as_millis() as usize);
+
+        // most recently read ID per stream key, kept for the offset handoff should this loop
+        // ever need to resume a read without relying on the consumer group's own position
+        let mut last_ids: HashMap<String, String> = HashMap::new();
+        let mut buf: Vec<(String, String, Result<Message, Error>)> = Vec::with_capacity(self.batch);
+
+        let mut backoff = INITIAL_BACKOFF;
+
+        loop {
+            let reply = match self.read(&group_opts).await {
+                Ok(reply) => reply,
+                Err(e @ Error::PoolUnavailable(_)) => return Err(e),
+                Err(e) => {
+                    warn!(
+                        "XREADGROUP on stream '{}' failed, retrying in {:?}: {}",
+                        self.stream, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            };
+
+            backoff = INITIAL_BACKOFF;
+
+            // an elapsed BLOCK timeout comes back as an empty reply, just loop again
+            buf.clear();
+            for StreamKey { key, ids } in reply.keys {
+                for StreamId { id, map } in ids {
+                    last_ids.insert(key.clone(), id.clone());
+                    buf.push((key.clone(), id, Message::try_from(map)));
+                }
+            }
+
+            debug!("fetched {} entries from stream '{}'", buf.len(), self.stream);
+
+            for (key, id, message) in buf.drain(..) {
+                if tx.send(FetchedEntry { key, id, message }).await.is_err() {
+                    // the counting loop terminated; stop pulling from Redis
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Reclaim abandoned work, then run the fetcher and counting loop concurrently: a background
+    /// task issues `XREADGROUP` and pushes decoded entries into a bounded channel, while this loop
+    /// drains the channel, calls `handle` for every successfully decoded [`Message`] (its stream
+    /// entry ID is passed along so callers can dedup redelivered entries) and dead-letters
+    /// anything that doesn't decode. `handle` is only `XACK`ed for after it returns, so a crash
+    /// mid-processing leaves the entry pending and it gets reclaimed on the next startup. Returns
+    /// once `handle` reports [`Progress::Terminate`] or the fetcher hits a fatal error.
+    pub async fn run<F, Fut>(&self, mut handle: F) -> Result<(), Error>
+    where
+        F: FnMut(&str, Message) -> Fut,
+        Fut: Future<Output = Progress>,
+    {
+        self.ensure_group().await?;
+
+        if self.reclaim(&mut handle).await? {
+            return Ok(());
+        }
+
+        let (tx, mut rx) = mpsc::channel(self.batch * CHANNEL_CAPACITY_FACTOR);
+
+        let fetcher = self.clone();
+        let fetch_task = tokio::spawn(async move { fetcher.fetch_loop(tx).await });
+
+        while let Some(FetchedEntry { id, message, .. }) = rx.recv().await {
+            match message {
+                Ok(msg) => {
+                    let terminate = matches!(handle(&id, msg).await, Progress::Terminate);
+                    self.xack(&id).await;
+                    if terminate {
+                        fetch_task.abort();
+                        return Ok(());
+                    }
+                }
+                Err(e) => self.dead_letter(&id, &e.to_string()).await,
+            }
+        }
+
+        // the channel closed because the fetcher returned; surface whatever it returned
+        match fetch_task.await {
+            Ok(result) => result,
+            Err(e) => Err(Error::TaskPanicked(e.to_string())),
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use futures_util::{Stream, StreamExt};
+use redis::{AsyncCommands, Commands};
+use tracing::{debug, warn};
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+use crate::{Error, Message, Progress, RedisBackend};
+
+const VERSION_KEY: &str = "version";
+const STATE_LATEST_KEY: &str = "state:latest";
+
+/// The key a checkpoint's word counts are stored under: `state:{version}`. An incrementing
+/// checkpoint writes a new key and leaves the earlier versions' keys untouched; a
+/// non-incrementing one (e.g. on `Terminate`) replaces the snapshot under the current version.
+fn state_key(version: &str) -> String {
+    format!("state:{}", version)
+}
+
+/// Atomically (a) bump `VERSION_KEY` iff `inc_count`, (b) write `state` under the resulting
+/// version's key, and (c) point `STATE_LATEST_KEY` at it. Run as a single Lua script so a crash
+/// can never leave the version counter, the snapshot and the "latest" pointer inconsistent with
+/// one another.
+const CHECKPOINT_SCRIPT: &str = r#"
+local version
+if ARGV[1] == '1' then
+  version = redis.call('INCR', KEYS[1])
+else
+  version = redis.call('GET', KEYS[1])
+  if not version then
+    version = 0
+  end
+end
+
+local state_key = 'state:' .. version
+
+if #ARGV >= 2 then
+  redis.call('DEL', state_key)
+  redis.call('HSET', state_key, unpack(ARGV, 2))
+end
+
+redis.call('SET', KEYS[2], version)
+
+return version
+"#;
+
+/// What [`CounterState::apply`] decided to do with a [`Message`], for the caller to act on.
+/// Checkpointing is I/O, so it's kept out of `apply` and reported here instead, which is what
+/// keeps `apply` synchronous and unit-testable without a live Redis connection.
+pub struct Outcome {
+    pub progress: Progress,
+    /// `Some(inc_count)` if this message should trigger a checkpoint.
+    pub checkpoint: Option<bool>,
+}
+
+/// Pure in-memory word-count state plus the at-least-once dedup set. Carries no I/O, so it can be
+/// driven directly in unit tests without a Redis connection.
+#[derive(Default)]
+pub struct CounterState {
+    // alternatively this could use a trie
+    state: HashMap<String, usize>,
+    /// Stream entry IDs already folded into `state`, used to drop redelivered entries: since
+    /// delivery is at-least-once, the same entry can reach `apply` more than once (e.g. after a
+    /// crash mid-processing gets it reclaimed).
+    ///
+    /// This can't be a single monotonic high-water mark: a consumer group splits the stream
+    /// across several `Counter`s, so entries can reach any one of them out of global ID order
+    /// (e.g. `Consumer::reclaim` hands a consumer entries abandoned by a different, crashed
+    /// consumer, with IDs lower than anything it has seen itself). Set membership dedups
+    /// correctly regardless of which consumer an entry was originally dispatched to.
+    processed_ids: HashSet<String>,
+}
+
+impl CounterState {
+    pub fn counts(&self) -> &HashMap<String, usize> {
+        &self.state
+    }
+
+    fn as_checkpoint(&self) -> Vec<(String, usize)> {
+        self.state.iter().map(|(w, c)| (w.clone(), *c)).collect()
+    }
+
+    fn update(&mut self, data: String) {
+        for w in data.split_whitespace().map(|w| w.to_lowercase()) {
+            *self.state.entry(w).or_default() += 1;
+        }
+    }
+
+    /// Dedups redelivered entries, folds `Count` data into `state`, and reports whether/how the
+    /// caller should checkpoint.
+    pub fn apply(&mut self, id: &str, msg: Message) -> Outcome {
+        if !self.processed_ids.insert(id.to_string()) {
+            debug!("SKIP: entry {} already folded into state (redelivered)", id);
+            return Outcome {
+                progress: Progress::Continue,
+                checkpoint: None,
+            };
+        }
+
+        debug!("Received message {:?}", &msg);
+
+        match msg {
+            Message::Count { client_id, data } => {
+                debug!(
+                    "Counting words form client '{}' input: '{}'",
+                    client_id, &data
+                );
+                self.update(data);
+                Outcome {
+                    progress: Progress::Continue,
+                    checkpoint: None,
+                }
+            }
+
+            Message::Disconnect { client_id } => {
+                debug!(
+                    "Client '{}' disconnected, checkpointing state: {:?}...",
+                    client_id, &self.state
+                );
+                // save current state and increment checkpoint counter
+                Outcome {
+                    progress: Progress::Continue,
+                    checkpoint: Some(true),
+                }
+            }
+
+            Message::Terminate => {
+                debug!(
+                    "Shutdown signal received, saving final checkpoint: {:?}",
+                    &self.state
+                );
+                // save current state but don't increment checkpoint counter
+                Outcome {
+                    progress: Progress::Terminate,
+                    checkpoint: Some(false),
+                }
+            }
+        }
+    }
+}
+
+/// Word-count state backed by Redis for checkpointing and restore. [`CounterState::apply`] stays
+/// pure; this just adds the I/O needed to persist and rehydrate it.
+pub struct Counter {
+    inner: CounterState,
+    backend: RedisBackend,
+}
+
+impl Counter {
+    /// Rehydrate state from the checkpoint `state:latest` points at, or start empty if no
+    /// checkpoint has been written yet.
+    pub async fn restore(backend: RedisBackend) -> Result<Self, Error> {
+        let state = match &backend {
+            RedisBackend::Single(conn) => {
+                let mut conn = conn.clone();
+                let version: Option<String> = conn.get(STATE_LATEST_KEY).await?;
+                match version {
+                    Some(version) => conn.hgetall(state_key(&version)).await?,
+                    None => HashMap::new(),
+                }
+            }
+            RedisBackend::Cluster(client) => {
+                let client = client.clone();
+                tokio::task::spawn_blocking(move || -> Result<_, Error> {
+                    let mut conn = client
+                        .get_connection()
+                        .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
+                    let version: Option<String> = conn.get(STATE_LATEST_KEY)?;
+                    match version {
+                        Some(version) => Ok(conn.hgetall(state_key(&version))?),
+                        None => Ok(HashMap::new()),
+                    }
+                })
+                .await
+                .map_err(|e| Error::TaskPanicked(e.to_string()))??
+            }
+        };
+
+        Ok(Self {
+            inner: CounterState {
+                state,
+                processed_ids: HashSet::new(),
+            },
+            backend,
+        })
+    }
+
+    #[inline]
+    pub fn state(&self) -> &HashMap<String, usize> {
+        self.inner.counts()
+    }
+
+    /// See [`CounterState::apply`].
+    pub fn apply(&mut self, id: &str, msg: Message) -> Outcome {
+        self.inner.apply(id, msg)
+    }
+
+    pub async fn checkpoint(&mut self, inc_count: bool) -> Result<(), Error> {
+        let state = self.inner.as_checkpoint();
+
+        // numkeys=2 (VERSION_KEY, STATE_LATEST_KEY), then the inc flag and the flattened state
+        let mut cmd = redis::cmd("EVAL");
+        cmd.arg(CHECKPOINT_SCRIPT)
+            .arg(2)
+            .arg(VERSION_KEY)
+            .arg(STATE_LATEST_KEY)
+            .arg(if inc_count { "1" } else { "0" });
+
+        for (word, count) in &state {
+            cmd.arg(word).arg(*count);
+        }
+
+        match &self.backend {
+            RedisBackend::Single(conn) => {
+                let mut conn = conn.clone();
+                let _version: usize = cmd
+                    .query_async(&mut conn)
+                    .await
+                    .map_err(|e| Error::Checkpoint(e.to_string()))?;
+            }
+            RedisBackend::Cluster(client) => {
+                let client = client.clone();
+                tokio::task::spawn_blocking(move || -> Result<(), Error> {
+                    let mut conn = client
+                        .get_connection()
+                        .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
+                    let _version: usize = cmd
+                        .query(&mut conn)
+                        .map_err(|e| Error::Checkpoint(e.to_string()))?;
+                    Ok(())
+                })
+                .await
+                .map_err(|e| Error::TaskPanicked(e.to_string()))??;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Drive `messages` through `state`, invoking `checkpoint` whenever [`CounterState::apply`] asks
+/// for one (passed the state snapshot to persist), and stopping once [`Progress::Terminate`] is
+/// reported or `shutdown` resolves first (which also forces one final, non-incrementing
+/// checkpoint). Generic over both the message source and the checkpoint action, so the
+/// apply-then-maybe-checkpoint wiring that `counter.rs` otherwise has to inline directly inside
+/// its `Consumer::run` handler can be unit-tested against an in-memory stream and a no-op
+/// checkpoint, without a live Redis connection or a running process.
+///
+/// This is deliberately *not* wired in as `Consumer`'s production loop: `Consumer::run` also owns
+/// `XACK`/dead-letter/reclaim bookkeeping per entry, which a bare `Stream<Item = Message>` has no
+/// way to express, so using this loop in place of `Consumer::run` would silently drop the
+/// at-least-once delivery guarantee. Production keeps calling `Counter::apply`/`Counter::checkpoint`
+/// from inside the handler it gives to `Consumer::run`; this function exists so that wiring can be
+/// exercised in isolation.
+pub async fn run_counter<S, C, Fut>(
+    state: &mut CounterState,
+    mut messages: S,
+    mut shutdown: impl Future<Output = ()> + Unpin,
+    mut checkpoint: C,
+) -> Progress
+where
+    S: Stream<Item = (String, Message)> + Unpin,
+    C: FnMut(bool, &CounterState) -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    loop {
+        let next = tokio::select! {
+            biased;
+            _ = &mut shutdown => None,
+            item = messages.next() => item,
+        };
+
+        let (id, msg) = match next {
+            Some(item) => item,
+            None => {
+                // either the source ended or shutdown resolved first
+                if let Err(e) = checkpoint(false, state).await {
+                    warn!("checkpoint failed: {}", e);
+                }
+                return Progress::Terminate;
+            }
+        };
+
+        let outcome = state.apply(&id, msg);
+
+        if let Some(inc_count) = outcome.checkpoint {
+            if let Err(e) = checkpoint(inc_count, state).await {
+                warn!("checkpoint failed: {}", e);
+            }
+        }
+
+        if matches!(outcome.progress, Progress::Terminate) {
+            return Progress::Terminate;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_counts_words_case_insensitively() {
+        let mut state = CounterState::default();
+
+        state.apply(
+            "1-0",
+            Message::Count {
+                client_id: "c1".into(),
+                data: "Hello hello World".into(),
+            },
+        );
+
+        assert_eq!(state.counts().get("hello"), Some(&2));
+        assert_eq!(state.counts().get("world"), Some(&1));
+    }
+
+    #[test]
+    fn apply_skips_redelivered_entries() {
+        let mut state = CounterState::default();
+        let msg = || Message::Count {
+            client_id: "c1".into(),
+            data: "hello".into(),
+        };
+
+        state.apply("5-0", msg());
+        assert_eq!(state.counts().get("hello"), Some(&1));
+
+        // exact redelivery of the same entry must not be folded in twice
+        state.apply("5-0", msg());
+        assert_eq!(state.counts().get("hello"), Some(&1));
+    }
+
+    #[test]
+    fn apply_folds_in_lower_ids_seen_for_the_first_time() {
+        // a consumer group splits the stream across several Counters, so a reclaimed entry can
+        // reach this Counter with an ID lower than anything it has already applied (e.g. it was
+        // originally dispatched to, and abandoned by, a different, crashed consumer). Dedup must
+        // not mistake that for a redelivery of an entry this Counter already folded in.
+        let mut state = CounterState::default();
+        let msg = || Message::Count {
+            client_id: "c1".into(),
+            data: "hello".into(),
+        };
+
+        state.apply("5-0", msg());
+        state.apply("3-0", msg());
+        assert_eq!(state.counts().get("hello"), Some(&2));
+    }
+
+    #[test]
+    fn apply_reports_checkpoint_and_progress_per_message() {
+        let mut state = CounterState::default();
+
+        let count = state.apply(
+            "1-0",
+            Message::Count {
+                client_id: "c1".into(),
+                data: "hi".into(),
+            },
+        );
+        assert!(count.checkpoint.is_none());
+        assert!(matches!(count.progress, Progress::Continue));
+
+        let disconnect = state.apply(
+            "2-0",
+            Message::Disconnect {
+                client_id: "c1".into(),
+            },
+        );
+        assert_eq!(disconnect.checkpoint, Some(true));
+        assert!(matches!(disconnect.progress, Progress::Continue));
+
+        let terminate = state.apply("3-0", Message::Terminate);
+        assert_eq!(terminate.checkpoint, Some(false));
+        assert!(matches!(terminate.progress, Progress::Terminate));
+    }
+
+    #[tokio::test]
+    async fn run_counter_folds_messages_and_checkpoints_until_the_stream_ends() {
+        let mut state = CounterState::default();
+        let messages = futures_util::stream::iter(vec![
+            (
+                "1-0".to_string(),
+                Message::Count {
+                    client_id: "c1".into(),
+                    data: "hello world".into(),
+                },
+            ),
+            (
+                "2-0".to_string(),
+                Message::Disconnect {
+                    client_id: "c1".into(),
+                },
+            ),
+        ]);
+
+        let mut checkpoints = Vec::new();
+        let progress = run_counter(
+            &mut state,
+            messages,
+            futures_util::future::pending(),
+            |inc_count, _state| {
+                checkpoints.push(inc_count);
+                async { Ok(()) }
+            },
+        )
+        .await;
+
+        assert!(matches!(progress, Progress::Terminate));
+        assert_eq!(state.counts().get("hello"), Some(&1));
+        // Disconnect's incrementing checkpoint, then the final non-incrementing one once the
+        // stream (a finite Vec) is drained
+        assert_eq!(checkpoints, vec![true, false]);
+    }
+
+    #[tokio::test]
+    async fn run_counter_stops_as_soon_as_a_message_reports_terminate() {
+        let mut state = CounterState::default();
+        let messages = futures_util::stream::iter(vec![
+            ("1-0".to_string(), Message::Terminate),
+            (
+                "2-0".to_string(),
+                Message::Count {
+                    client_id: "c1".into(),
+                    data: "never counted".into(),
+                },
+            ),
+        ]);
+
+        let mut checkpoints = 0;
+        let progress = run_counter(
+            &mut state,
+            messages,
+            futures_util::future::pending(),
+            |_inc_count, _state| {
+                checkpoints += 1;
+                async { Ok(()) }
+            },
+        )
+        .await;
+
+        assert!(matches!(progress, Progress::Terminate));
+        assert_eq!(checkpoints, 1, "must not keep draining the stream past Terminate");
+        assert!(state.counts().get("never").is_none());
+    }
+}
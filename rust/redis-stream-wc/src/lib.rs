@@ -1,14 +1,76 @@
-use bb8_redis::{bb8, RedisConnectionManager};
-
-use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use redis::cluster::ClusterClient;
+use redis::{AsyncCommands, Commands};
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use thiserror::Error as ThisError;
+
+mod consumer;
+mod counting;
+
+pub use consumer::{Consumer, Progress};
+pub use counting::{run_counter, Counter, CounterState, Outcome};
+
+/// Crate-wide error covering everything that can go wrong sending, decoding or checkpointing
+/// messages. Kept as a flat set of causes (rather than wrapping each call site's error type
+/// separately) so callers can match on [`Error::PoolUnavailable`] to tell a fatal, give-up-now
+/// condition apart from the rest, which are worth retrying.
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("could not acquire a Redis connection: {0}")]
+    PoolUnavailable(String),
+
+    #[error("Redis command failed: {0}")]
+    Redis(#[from] redis::RedisError),
+
+    #[error("failed to decode a stream entry into a Message: {0}")]
+    Decode(String),
+
+    #[error("checkpoint failed: {0}")]
+    Checkpoint(String),
+
+    #[error("background task panicked: {0}")]
+    TaskPanicked(String),
+}
 
 pub const DEAULT_REDIS_URL: &str = "redis://localhost:6379/";
 pub const DEAULT_STREAM_ID: &str = "counter";
 pub const REDIS_MSG_KEY: &str = "msg";
 
+/// A handle to either a single-node Redis instance or a Redis Cluster deployment.
+///
+/// `send` (and the consumer counterpart) dispatch on this so the stream itself can be sharded
+/// across a cluster without callers having to know about it.
+///
+/// The single-node case holds one [`MultiplexedConnection`], not a pool: it's cheap to clone and
+/// pipelines every concurrent command over a single socket, so callers can just clone it instead
+/// of paying pool-acquisition latency on every command.
+#[derive(Clone)]
+pub enum RedisBackend {
+    Single(MultiplexedConnection),
+    Cluster(ClusterClient),
+}
+
+impl RedisBackend {
+    /// Build a backend from a comma-separated list of node URLs.
+    ///
+    /// When `cluster` is `true` every URL is treated as a cluster seed node, otherwise only the
+    /// first URL is used to open a single multiplexed connection.
+    pub async fn connect(urls: &str, cluster: bool) -> Result<Self, redis::RedisError> {
+        if cluster {
+            let nodes: Vec<&str> = urls.split(',').map(str::trim).collect();
+            let client = ClusterClient::new(nodes)?;
+            Ok(Self::Cluster(client))
+        } else {
+            let url = urls.split(',').next().unwrap_or(urls).trim().to_owned();
+            let client = redis::Client::open(url)?;
+            let conn = client.get_multiplexed_async_connection().await?;
+            Ok(Self::Single(conn))
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Message {
     Count { client_id: String, data: String },
@@ -19,53 +81,68 @@ pub enum Message {
 }
 
 impl TryFrom<Vec<u8>> for Message {
-    type Error = String;
+    type Error = Error;
 
     #[inline(always)]
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        serde_json::from_slice(&value).map_err(|e| e.to_string())
+        serde_json::from_slice(&value).map_err(|e| Error::Decode(e.to_string()))
     }
 }
 
 impl TryFrom<redis::Value> for Message {
-    type Error = String;
+    type Error = Error;
 
     #[inline]
     fn try_from(value: redis::Value) -> Result<Self, Self::Error> {
         if let redis::Value::Data(bytes) = value {
             bytes.try_into()
         } else {
-            Err(format!("Invalid Redis payload: {:?}", value))
+            Err(Error::Decode(format!("Invalid Redis payload: {:?}", value)))
         }
     }
 }
 
 impl TryFrom<HashMap<String, redis::Value>> for Message {
-    type Error = String;
+    type Error = Error;
 
     #[inline]
     fn try_from(mut value: HashMap<String, redis::Value>) -> Result<Self, Self::Error> {
         if let Some(v) = value.remove(REDIS_MSG_KEY) {
             v.try_into()
         } else {
-            Err(format!("No {} key in Redis Stream entry", REDIS_MSG_KEY))
+            Err(Error::Decode(format!(
+                "No {} key in Redis Stream entry",
+                REDIS_MSG_KEY
+            )))
         }
     }
 }
 
-pub async fn send(pool: &bb8::Pool<RedisConnectionManager>, receiver: String, msg: Message) {
+pub async fn send(backend: &RedisBackend, receiver: String, msg: Message) -> Result<(), Error> {
     // Note: even better would be serializing directly into bytes
     let payload = serde_json::to_string(&msg).expect("message serialization");
 
-    let pool = pool.clone();
+    match backend {
+        RedisBackend::Single(conn) => {
+            let mut conn = conn.clone();
+            conn.xadd(receiver, "*", &[(REDIS_MSG_KEY, payload)])
+                .await
+                .map_err(Error::from)
+        }
+        RedisBackend::Cluster(client) => {
+            // the sync cluster client has no async counterpart yet, so hop onto a blocking
+            // thread rather than stalling the executor
+            let client = client.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut conn = client
+                    .get_connection()
+                    .map_err(|e| Error::PoolUnavailable(e.to_string()))?;
 
-    tokio::spawn(async move {
-        let mut conn = pool.get().await.expect("Redis connection");
-        let _: () = conn
-            .xadd(receiver, "*", &[(REDIS_MSG_KEY, payload)])
+                conn.xadd(receiver, "*", &[(REDIS_MSG_KEY, payload)])
+                    .map_err(Error::from)
+            })
             .await
-            .expect("XADD: payload sent");
-    })
-    .await
-    .expect("message sent");
+            .map_err(|e| Error::TaskPanicked(e.to_string()))?
+        }
+    }
 }
@@ -0,0 +1,136 @@
+//! End-to-end coverage for the `counter`/`client` binaries: a throwaway Redis is started per
+//! test, `client` is run against it to produce messages, `counter` is run (as a subprocess, since
+//! it blocks serving the stream) to consume them, and the test asserts on the `state:*`/`version`
+//! keys `counter` leaves behind in Redis. This is the only thing unit tests on `CounterState`
+//! can't cover: that the checkpoint actually round-trips through real Redis and that a restarted
+//! `counter` picks up where the last one left off.
+
+use assert_cmd::Command as AssertCommand;
+use redis::Commands;
+use testcontainers::{clients::Cli, images::redis::Redis};
+
+use std::collections::HashMap;
+use std::process::{Child, Command};
+use std::time::Duration;
+
+const COUNTER_BIN: &str = env!("CARGO_BIN_EXE_counter");
+const CLIENT_BIN: &str = env!("CARGO_BIN_EXE_client");
+
+/// Spawns `counter` against `redis_url`/`stream_id` and kills it on drop, so a test that panics
+/// (or an assertion that fails) before sending `Terminate` can't leak the process.
+struct CounterProcess(Child);
+
+impl CounterProcess {
+    fn spawn(redis_url: &str, stream_id: &str, consumer_id: &str) -> Self {
+        let child = Command::new(COUNTER_BIN)
+            .env("REDIS_URL", redis_url)
+            .env("STREAM_ID", stream_id)
+            .env("CONSUMER_ID", consumer_id)
+            .env("RUST_LOG", "error")
+            .spawn()
+            .expect("failed to spawn counter binary");
+        Self(child)
+    }
+
+    fn wait_for_exit(&mut self) {
+        self.0
+            .wait()
+            .expect("counter process failed to run to completion");
+    }
+
+    /// Simulates a crash: no `Terminate` is sent, the process is just killed.
+    fn kill(mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+impl Drop for CounterProcess {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn run_client(redis_url: &str, stream_id: &str, client_id: &str, repeats: usize) {
+    AssertCommand::new(CLIENT_BIN)
+        .env("REDIS_URL", redis_url)
+        .env("STREAM_ID", stream_id)
+        .env("CLIENT_ID", client_id)
+        .env("REPEATS", repeats.to_string())
+        .env("RUST_LOG", "error")
+        .assert()
+        .success();
+}
+
+fn send_terminate(redis_url: &str, stream_id: &str) {
+    let client = redis::Client::open(redis_url).unwrap();
+    let mut conn = client.get_connection().unwrap();
+    let payload = serde_json::to_string(&serde_json::json!("Terminate")).unwrap();
+    let _: String = conn.xadd(stream_id, "*", &[("msg", payload)]).unwrap();
+}
+
+fn latest_state(redis_url: &str) -> (usize, HashMap<String, usize>) {
+    let client = redis::Client::open(redis_url).unwrap();
+    let mut conn = client.get_connection().unwrap();
+    let version: usize = conn.get("version").unwrap_or(0);
+    let state: HashMap<String, usize> = conn.hgetall(format!("state:{}", version)).unwrap();
+    (version, state)
+}
+
+#[test]
+fn counter_checkpoints_final_state_and_version_on_terminate() {
+    let docker = Cli::default();
+    let redis = docker.run(Redis::default());
+    let redis_url = format!("redis://127.0.0.1:{}", redis.get_host_port_ipv4(6379));
+
+    let stream_id = "counter-test-terminate";
+    let mut counter = CounterProcess::spawn(&redis_url, stream_id, "test-consumer");
+    // the consumer group starts at `$`, so give `counter` a moment to create it before sending
+    std::thread::sleep(Duration::from_millis(500));
+
+    run_client(&redis_url, stream_id, "client-a", 3);
+
+    // `Disconnect` above already triggers an incrementing checkpoint; `Terminate` forces a final
+    // non-incrementing one and makes the counter process exit.
+    send_terminate(&redis_url, stream_id);
+    counter.wait_for_exit();
+
+    let (version, state) = latest_state(&redis_url);
+    assert_eq!(version, 1, "Disconnect should have incremented the version once");
+    assert_eq!(state.get("hello"), Some(&3));
+    assert_eq!(state.get("world"), Some(&3));
+}
+
+#[test]
+fn counter_restores_from_checkpoint_after_a_crash() {
+    let docker = Cli::default();
+    let redis = docker.run(Redis::default());
+    let redis_url = format!("redis://127.0.0.1:{}", redis.get_host_port_ipv4(6379));
+
+    let stream_id = "counter-test-restore";
+
+    let counter = CounterProcess::spawn(&redis_url, stream_id, "test-consumer");
+    // the consumer group starts at `$`, so give `counter` a moment to create it before sending
+    std::thread::sleep(Duration::from_millis(500));
+    run_client(&redis_url, stream_id, "client-a", 2);
+    // give the running counter a moment to fold the Disconnect's checkpoint before the crash
+    std::thread::sleep(Duration::from_millis(500));
+    counter.kill();
+
+    let (version, state) = latest_state(&redis_url);
+    assert_eq!(version, 1);
+    assert_eq!(state.get("hello"), Some(&2));
+
+    // restart against the same stream/Redis: `counter` should rehydrate from `state:1` before
+    // folding in any new messages
+    let mut counter = CounterProcess::spawn(&redis_url, stream_id, "test-consumer");
+    run_client(&redis_url, stream_id, "client-b", 1);
+    send_terminate(&redis_url, stream_id);
+    counter.wait_for_exit();
+
+    let (version, state) = latest_state(&redis_url);
+    assert_eq!(version, 2, "Disconnect from client-b should bump the version again");
+    assert_eq!(state.get("hello"), Some(&3), "restored count plus the new message");
+    assert_eq!(state.get("world"), Some(&3));
+}